@@ -2,11 +2,17 @@ use crate::{
     command::{sample_encode, PROGRESS_CHARS},
     console_ext::style,
 };
-use anyhow::{bail, ensure};
+use anyhow::{bail, ensure, Context};
 use clap::Parser;
 use console::style;
 use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
-use std::{path::PathBuf, time::Duration};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{process::Command, sync::Semaphore};
 
 const BAR_LEN: u64 = 1000;
 
@@ -15,7 +21,7 @@ const BAR_LEN: u64 = 1000;
 ///
 /// Outputs:
 /// * Best crf value
-/// * Mean sample VMAF score
+/// * Sample VMAF score (mean, or the configured per-frame percentile)
 /// * Predicted full encode size
 /// * Predicted full encode time
 #[derive(Parser)]
@@ -48,6 +54,112 @@ pub struct Args {
     /// More samples take longer but may provide a more accurate result.
     #[clap(long, default_value_t = 3)]
     pub samples: u64,
+
+    /// Percentile of the per-frame VMAF distribution to optimize against,
+    /// instead of the arithmetic mean. A low percentile protects worst-case
+    /// quality in dark/complex scenes. Use 100 to target the mean.
+    #[clap(long, default_value_t = 25.0)]
+    pub vmaf_percentile: f32,
+
+    /// Detect scene cuts and search for the best crf independently per scene,
+    /// applying a different crf to each segment instead of one global crf.
+    #[clap(long)]
+    pub scenes: bool,
+
+    /// Scene-cut detection sensitivity, passed to ffmpeg's `select='gt(scene,N)'`.
+    /// Higher values detect fewer cuts. Only used with --scenes.
+    #[clap(long, default_value_t = 0.4)]
+    pub scene_threshold: f32,
+
+    /// Number of sample-encode probes to run concurrently. With more than one
+    /// worker the exploratory phase probes several crf candidates in parallel.
+    #[clap(long, default_value_t = 1)]
+    pub workers: usize,
+
+    /// Maximum attempts for a single sample-encode probe. A transient
+    /// encoder/ffmpeg crash is retried until this many attempts have been made
+    /// in all, then propagates.
+    #[clap(long, default_value_t = 2)]
+    pub max_tries: u32,
+
+    /// Encoder backend to search & drive.
+    #[clap(long, arg_enum, default_value_t = Encoder::SvtAv1)]
+    pub encoder: Encoder,
+
+    /// Output format for the final result. `human` prints a readable summary,
+    /// `json` emits a stable JSON object on stdout (progress stays on stderr).
+    #[clap(long, arg_enum, default_value_t = StdoutFormat::Human)]
+    pub stdout_format: StdoutFormat,
+}
+
+/// Supported encoder backends. Each knows its ffmpeg library, default passes &
+/// arguments, and the CLI flag carrying its integer quality knob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+pub enum Encoder {
+    Aom,
+    SvtAv1,
+    X264,
+    X265,
+    Rav1e,
+    Vpx,
+}
+
+impl Encoder {
+    /// Short name as accepted on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Aom => "aom",
+            Self::SvtAv1 => "svt-av1",
+            Self::X264 => "x264",
+            Self::X265 => "x265",
+            Self::Rav1e => "rav1e",
+            Self::Vpx => "vpx",
+        }
+    }
+
+    /// CLI flag that carries the integer quality knob for this encoder.
+    pub fn quality_flag(self) -> &'static str {
+        match self {
+            Self::Aom | Self::Vpx => "--cq-level",
+            Self::SvtAv1 | Self::X264 | Self::X265 => "--crf",
+            Self::Rav1e => "--quantizer",
+        }
+    }
+
+    /// Default number of encoder passes.
+    pub fn default_passes(self) -> u8 {
+        match self {
+            Self::Vpx => 2,
+            _ => 1,
+        }
+    }
+
+    /// Default extra arguments appended when driving this encoder.
+    pub fn default_args(self) -> &'static [&'static str] {
+        match self {
+            // constant-quality mode needs an unbounded target bitrate
+            Self::Aom | Self::Vpx => &["-b:v", "0"],
+            _ => &[],
+        }
+    }
+
+    /// ffmpeg `-c:v` codec name driving this encoder.
+    pub fn codec_name(self) -> &'static str {
+        match self {
+            Self::Aom => "libaom-av1",
+            Self::SvtAv1 => "libsvtav1",
+            Self::X264 => "libx264",
+            Self::X265 => "libx265",
+            Self::Rav1e => "librav1e",
+            Self::Vpx => "libvpx-vp9",
+        }
+    }
+}
+
+impl fmt::Display for Encoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
 pub async fn crf_search(args: Args) -> anyhow::Result<()> {
@@ -58,30 +170,113 @@ pub async fn crf_search(args: Args) -> anyhow::Result<()> {
     );
     bar.enable_steady_tick(100);
 
-    let best = run(&args, &bar).await?;
+    if args.scenes {
+        let result = scene_search(&args, &bar).await?;
+        bar.finish();
+        match args.stdout_format {
+            StdoutFormat::Human => {
+                result.print_encode_hint(&args);
+                result.print_result(args.vmaf_percentile);
+            }
+            StdoutFormat::Json => print_scene_result_json(&result, &args)?,
+        }
+        return Ok(());
+    }
+
+    let mut crf_attempts = Vec::new();
+    let best = run_range(&args, None, &mut crf_attempts, &bar).await?;
 
     bar.finish();
 
-    // encode how-to hint + predictions
-    eprintln!(
-        "\n{} {}\n",
-        style("Encode with:").dim(),
-        style!(
-            "ab-av1 encode -i {:?} --crf {} --preset {}",
-            args.input,
-            best.crf,
-            args.preset,
-        )
-        .dim()
-        .italic()
-    );
+    match args.stdout_format {
+        StdoutFormat::Human => {
+            // encode how-to hint + predictions
+            eprintln!(
+                "\n{} {}\n",
+                style("Encode with:").dim(),
+                style!(
+                    "ab-av1 encode -i {:?} --encoder {} {} {} --preset {}",
+                    args.input,
+                    args.encoder,
+                    args.encoder.quality_flag(),
+                    best.crf,
+                    args.preset,
+                )
+                .dim()
+                .italic()
+            );
+            StdoutFormat::Human.print_result(&best, args.vmaf_percentile);
+        }
+        StdoutFormat::Json => print_result_json(&best, &crf_attempts, &args)?,
+    }
 
-    StdoutFormat::Human.print_result(&best);
+    Ok(())
+}
 
+/// Serialize the final sample and the full attempt history as a stable JSON
+/// object on stdout, for embedding in batch-encoding pipelines.
+fn print_result_json(
+    best: &Sample,
+    crf_attempts: &[Sample],
+    args: &Args,
+) -> anyhow::Result<()> {
+    let sample_json = |s: &Sample| {
+        serde_json::json!({
+            "crf": s.crf,
+            "vmaf": s.enc.vmaf,
+            "predicted_encode_size": s.enc.predicted_encode_size,
+            "predicted_encode_percent": s.enc.predicted_encode_percent,
+            "predicted_encode_seconds": s.enc.predicted_encode_time.as_secs_f64(),
+            "samples": s.samples,
+        })
+    };
+    let json = serde_json::json!({
+        "crf": best.crf,
+        "vmaf": best.enc.vmaf,
+        "vmaf_percentile": args.vmaf_percentile,
+        "encoder": args.encoder.name(),
+        "predicted_encode_size": best.enc.predicted_encode_size,
+        "predicted_encode_percent": best.enc.predicted_encode_percent,
+        "predicted_encode_seconds": best.enc.predicted_encode_time.as_secs_f64(),
+        "samples": best.samples,
+        "crf_attempts": crf_attempts.iter().map(sample_json).collect::<Vec<_>>(),
+    });
+    println!("{}", serde_json::to_string(&json)?);
+    Ok(())
+}
+
+/// Serialize a scene-aware search result as a stable JSON object on stdout. It
+/// shares `print_result_json`'s `vmaf_percentile`, `encoder` & prediction keys;
+/// in place of a single crf it lists each scene's range, crf & predictions, and
+/// carries no sample count or attempt history.
+fn print_scene_result_json(result: &SceneSearch, args: &Args) -> anyhow::Result<()> {
+    let scene_json = |scene: &Scene| {
+        serde_json::json!({
+            "start": scene.range.start.as_secs_f64(),
+            "end": scene.range.end.as_secs_f64(),
+            "crf": scene.sample.crf,
+            "vmaf": scene.sample.enc.vmaf,
+            "predicted_encode_size": scene.sample.enc.predicted_encode_size,
+            "predicted_encode_percent": scene.sample.enc.predicted_encode_percent,
+            "predicted_encode_seconds": scene.sample.enc.predicted_encode_time.as_secs_f64(),
+        })
+    };
+    let json = serde_json::json!({
+        "vmaf_percentile": args.vmaf_percentile,
+        "encoder": args.encoder.name(),
+        "predicted_encode_size": result.predicted_encode_size,
+        "predicted_encode_percent": result.predicted_encode_percent,
+        "predicted_encode_seconds": result.predicted_encode_time.as_secs_f64(),
+        "scenes": result.scenes.iter().map(scene_json).collect::<Vec<_>>(),
+    });
+    println!("{}", serde_json::to_string(&json)?);
     Ok(())
 }
 
-async fn run(
+/// Run the crf search against a single time range of the input, or the whole
+/// input when `range` is `None`. `crf_attempts` collects every probe made, in
+/// order, for the json output & history.
+async fn run_range(
     Args {
         input,
         preset,
@@ -90,7 +285,14 @@ async fn run(
         min_crf,
         max_crf,
         samples,
+        vmaf_percentile,
+        workers,
+        max_tries,
+        encoder,
+        ..
     }: &Args,
+    range: Option<TimeRange>,
+    crf_attempts: &mut Vec<Sample>,
     bar: &ProgressBar,
 ) -> anyhow::Result<Sample> {
     ensure!(min_crf <= max_crf, "Invalid --min-crf & --max-crf");
@@ -102,14 +304,65 @@ async fn run(
         samples: 1,
         keep: false,
         stdout_format: sample_encode::StdoutFormat::Json,
+        vmaf_percentile: *vmaf_percentile,
+        time_range: range.map(|r| (r.start, r.end)),
+        encoder: *encoder,
     };
 
     bar.set_length(BAR_LEN);
     let sample_bar = ProgressBar::hidden();
-    let mut crf_attempts = Vec::new();
     // if we're doing/did a 1-sample 3rd run
     let mut quick_3rd_run = false;
 
+    // bounded worker pool so probes can run concurrently without swamping the box
+    let pool = Arc::new(Semaphore::new((*workers).max(1)));
+    let max_tries = (*max_tries).max(1);
+
+    // with several workers, seed the search with a concurrent sweep of evenly
+    // spaced crfs so the spline predictor starts from a good picture of the curve
+    if *workers > 1 {
+        let seeds = seed_crfs(*min_crf, *max_crf, *workers);
+        let seed_total = seeds.len();
+        let mut tasks = tokio::task::JoinSet::new();
+        for crf in seeds {
+            let mut probe_args = args.clone();
+            probe_args.crf = crf;
+            probe_args.samples = 1;
+            let pool = pool.clone();
+            let sample_bar = sample_bar.clone();
+            tasks.spawn_local(async move {
+                let enc = probe(probe_args, pool, max_tries, sample_bar).await?;
+                anyhow::Ok((crf, enc))
+            });
+        }
+
+        // update the bar as each seed lands, rather than only once the whole
+        // sweep finishes, since several probes are now in flight at once
+        let mut seeded = Vec::with_capacity(seed_total);
+        while let Some(result) = tasks.join_next().await {
+            let (crf, enc) = result??;
+            seeded.push(Sample {
+                crf,
+                samples: 1,
+                enc,
+            });
+            bar.set_position((seeded.len() * BAR_LEN as usize / seed_total.max(1)) as _);
+            bar.set_message(format!(
+                "seeded {}/{} probes, ",
+                seeded.len(),
+                seed_total
+            ));
+        }
+        seeded.sort_by_key(|s| s.crf);
+        for sample in seeded {
+            sample.print_attempt(bar, *min_vmaf, *max_encoded_percent, *vmaf_percentile);
+            crf_attempts.push(sample);
+        }
+        if let Some(start) = seed_start_crf(crf_attempts.as_slice(), *min_vmaf) {
+            args.crf = start;
+        }
+    }
+
     for run in 1.. {
         // how much we're prepared to go higher than the min-vmaf
         let higher_tolerance = run as f32 * 0.2;
@@ -125,8 +378,12 @@ async fn run(
         };
 
         bar.set_message(format!("sampling crf {}, ", args.crf));
-        let mut sample_task =
-            tokio::task::spawn_local(sample_encode::run(args.clone(), sample_bar.clone()));
+        let mut sample_task = tokio::task::spawn_local(probe(
+            args.clone(),
+            pool.clone(),
+            max_tries,
+            sample_bar.clone(),
+        ));
 
         // TODO replace with channel updates
         let sample_task = loop {
@@ -168,7 +425,7 @@ async fn run(
                     return Ok(sample);
                 }
                 Some(upper) => {
-                    args.crf = vmaf_lerp_crf(*min_vmaf, upper, &sample);
+                    args.crf = vmaf_crf_spline(*min_vmaf, crf_attempts.as_slice(), upper, &sample);
                 }
                 None if sample.crf == *max_crf => {
                     return Ok(sample);
@@ -183,7 +440,7 @@ async fn run(
             if sample.enc.predicted_encode_percent > *max_encoded_percent as _
                 || sample.crf == *min_crf
             {
-                sample.print_attempt(bar, *min_vmaf, *max_encoded_percent);
+                sample.print_attempt(bar, *min_vmaf, *max_encoded_percent, *vmaf_percentile);
                 bail!("Failed to find a suitable crf");
             }
 
@@ -194,11 +451,11 @@ async fn run(
 
             match l_bound {
                 Some(lower) if lower.crf + 1 == sample.crf => {
-                    sample.print_attempt(bar, *min_vmaf, *max_encoded_percent);
+                    sample.print_attempt(bar, *min_vmaf, *max_encoded_percent, *vmaf_percentile);
                     return Ok(lower.clone());
                 }
                 Some(lower) => {
-                    args.crf = vmaf_lerp_crf(*min_vmaf, &sample, lower);
+                    args.crf = vmaf_crf_spline(*min_vmaf, crf_attempts.as_slice(), &sample, lower);
                 }
                 None if run == 1 && sample.crf > min_crf + 1 => {
                     args.crf = (min_crf + sample.crf) / 2;
@@ -206,11 +463,299 @@ async fn run(
                 None => args.crf = *min_crf,
             };
         }
-        sample.print_attempt(bar, *min_vmaf, *max_encoded_percent);
+        sample.print_attempt(bar, *min_vmaf, *max_encoded_percent, *vmaf_percentile);
     }
     unreachable!();
 }
 
+/// Run a single sample-encode probe behind the worker pool, retrying a
+/// transient encoder/ffmpeg failure for up to `max_tries` attempts in all so
+/// one flaky probe doesn't abort the whole search.
+async fn probe(
+    args: sample_encode::Args,
+    pool: Arc<Semaphore>,
+    max_tries: u32,
+    bar: ProgressBar,
+) -> anyhow::Result<sample_encode::Output> {
+    let _permit = pool.acquire().await.expect("worker pool is never closed");
+    let crf = args.crf;
+    let mut last = None;
+    for attempt in 1..=max_tries {
+        match sample_encode::run(args.clone(), bar.clone()).await {
+            Ok(out) => return Ok(out),
+            Err(err) => {
+                // `sample_encode::run` fails a command invocation with a
+                // `ProbeFailure` carrying the raw (possibly non-utf8) stderr;
+                // fall back to the stringified error for anything else.
+                let stderr = match err.downcast_ref::<ProbeFailure>() {
+                    Some(ProbeFailure(bytes)) => EncoderOutput::new(bytes.clone()),
+                    None => EncoderOutput::new(err.to_string().into_bytes()),
+                };
+                if attempt < max_tries {
+                    bar.println(format!(
+                        "crf {crf} probe failed (try {attempt}/{max_tries}), retrying: {stderr}"
+                    ));
+                }
+                last = Some(stderr);
+            }
+        }
+    }
+    bail!(
+        "sample-encode for crf {crf} failed after {max_tries} tries: {}",
+        last.expect("max_tries >= 1")
+    );
+}
+
+/// Raw encoder/ffmpeg stderr bytes from a failed sample-encode command, as
+/// returned by `sample_encode::run` so a probe failure can be rendered without
+/// lossily stringifying non-utf8 output first.
+#[derive(Debug)]
+pub(crate) struct ProbeFailure(pub(crate) Vec<u8>);
+
+impl fmt::Display for ProbeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes of stderr", self.0.len())
+    }
+}
+
+impl std::error::Error for ProbeFailure {}
+
+/// Captured encoder/ffmpeg stderr: decoded as UTF-8 where valid, otherwise kept
+/// as raw bytes, so a probe failure renders a clean message either way.
+#[derive(Debug)]
+enum EncoderOutput {
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+impl EncoderOutput {
+    fn new(bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(s) => Self::String(s),
+            Err(e) => Self::Bytes(e.into_bytes()),
+        }
+    }
+}
+
+impl fmt::Display for EncoderOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(s) => write!(f, "{}", s.trim()),
+            Self::Bytes(b) => write!(f, "{} bytes of non-utf8 stderr", b.len()),
+        }
+    }
+}
+
+/// Evenly spaced crf seeds strictly inside `[min_crf, max_crf]` before rounding
+/// to integers; in a narrow range a rounded, deduplicated seed can land on an
+/// endpoint.
+fn seed_crfs(min_crf: u8, max_crf: u8, workers: usize) -> Vec<u8> {
+    let n = workers.max(1);
+    let mut out = Vec::with_capacity(n);
+    for i in 1..=n {
+        let frac = i as f32 / (n + 1) as f32;
+        let crf = (min_crf as f32 + frac * (max_crf - min_crf) as f32).round() as u8;
+        if !out.contains(&crf) {
+            out.push(crf);
+        }
+    }
+    out
+}
+
+/// The highest crf (smallest file) among the seed probes that still meets the
+/// target, giving the refinement loop a tight bracket to start from.
+fn seed_start_crf(crf_attempts: &[Sample], min_vmaf: f32) -> Option<u8> {
+    crf_attempts
+        .iter()
+        .filter(|s| s.enc.vmaf >= min_vmaf)
+        .map(|s| s.crf)
+        .max()
+}
+
+/// A half-open time range `[start, end)` within the input.
+#[derive(Debug, Clone, Copy)]
+struct TimeRange {
+    start: Duration,
+    end: Duration,
+}
+
+impl TimeRange {
+    fn duration_secs(&self) -> f64 {
+        (self.end - self.start).as_secs_f64()
+    }
+}
+
+/// A detected scene together with the crf chosen for it.
+#[derive(Debug, Clone)]
+struct Scene {
+    range: TimeRange,
+    sample: Sample,
+}
+
+/// Aggregated result of a scene-aware search: the per-scene crf map plus the
+/// combined predictions for the whole encode.
+struct SceneSearch {
+    scenes: Vec<Scene>,
+    predicted_encode_size: u64,
+    predicted_encode_percent: f64,
+    predicted_encode_time: Duration,
+}
+
+/// Detect scene cuts and run the crf search independently per scene, then
+/// combine the per-scene predictions.
+async fn scene_search(args: &Args, bar: &ProgressBar) -> anyhow::Result<SceneSearch> {
+    let ranges = detect_scenes(&args.input, args.scene_threshold).await?;
+    ensure!(!ranges.is_empty(), "No scenes detected");
+
+    let mut scenes = Vec::with_capacity(ranges.len());
+    for (idx, range) in ranges.iter().enumerate() {
+        bar.set_message(format!("scene {}/{}, ", idx + 1, ranges.len()));
+        let mut crf_attempts = Vec::new();
+        let sample = run_range(args, Some(*range), &mut crf_attempts, bar).await?;
+        scenes.push(Scene {
+            range: *range,
+            sample,
+        });
+    }
+
+    // the per-scene predictions already cover their own range, so the full
+    // encode is their sum; each scene's percentage is already relative to the
+    // whole input's size, so the percentages sum too
+    let mut predicted_encode_size = 0_u64;
+    let mut predicted_encode_time = Duration::ZERO;
+    let mut predicted_encode_percent = 0.0;
+    for scene in &scenes {
+        predicted_encode_size += scene.sample.enc.predicted_encode_size;
+        predicted_encode_time += scene.sample.enc.predicted_encode_time;
+        predicted_encode_percent += scene.sample.enc.predicted_encode_percent;
+    }
+
+    Ok(SceneSearch {
+        scenes,
+        predicted_encode_size,
+        predicted_encode_percent,
+        predicted_encode_time,
+    })
+}
+
+impl SceneSearch {
+    fn print_encode_hint(&self, args: &Args) {
+        eprintln!("\n{}", style("Encode with per-scene crf:").dim());
+        for (idx, scene) in self.scenes.iter().enumerate() {
+            eprintln!(
+                "  {}",
+                style!(
+                    "scene {} [{:.2}s-{:.2}s] --encoder {} {} {} --preset {}",
+                    idx + 1,
+                    scene.range.start.as_secs_f64(),
+                    scene.range.end.as_secs_f64(),
+                    args.encoder,
+                    args.encoder.quality_flag(),
+                    scene.sample.crf,
+                    args.preset,
+                )
+                .dim()
+                .italic()
+            );
+        }
+        eprintln!();
+    }
+
+    fn print_result(&self, vmaf_percentile: f32) {
+        let vmaf_label = vmaf_aggregate_label(vmaf_percentile);
+        for (idx, scene) in self.scenes.iter().enumerate() {
+            let crf = style(scene.sample.crf).bold().green();
+            let vmaf = style(scene.sample.enc.vmaf).bold();
+            println!(
+                "scene {} {:.2}s-{:.2}s crf {crf} {vmaf_label} {vmaf:.2}",
+                idx + 1,
+                scene.range.start.as_secs_f64(),
+                scene.range.end.as_secs_f64(),
+            );
+        }
+        let size = style(HumanBytes(self.predicted_encode_size)).bold().green();
+        let percent = style!("{}%", self.predicted_encode_percent.round())
+            .bold()
+            .green();
+        let time = style(HumanDuration(self.predicted_encode_time)).bold();
+        println!(
+            "{} scenes predicted full encode size {size} ({percent}) taking {time}",
+            self.scenes.len()
+        );
+    }
+}
+
+/// Detect scene-cut boundaries with ffmpeg, returning contiguous ranges that
+/// together cover the whole input.
+async fn detect_scenes(input: &Path, threshold: f32) -> anyhow::Result<Vec<TimeRange>> {
+    let duration = input_duration(input).await?;
+
+    let out = Command::new("ffmpeg")
+        .arg("-i")
+        .arg(input)
+        .arg("-filter:v")
+        .arg(format!("select='gt(scene,{threshold})',showinfo"))
+        .arg("-f")
+        .arg("null")
+        .arg("-")
+        .output()
+        .await
+        .context("ffmpeg scene detection")?;
+
+    // ffmpeg writes `showinfo` lines to stderr, each carrying a `pts_time:<secs>`
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let mut cuts: Vec<Duration> = stderr
+        .lines()
+        .filter_map(parse_pts_time)
+        .filter(|&t| t > Duration::ZERO && t < duration)
+        .collect();
+    cuts.sort();
+    cuts.dedup();
+
+    let mut ranges = Vec::with_capacity(cuts.len() + 1);
+    let mut start = Duration::ZERO;
+    for cut in cuts {
+        ranges.push(TimeRange { start, end: cut });
+        start = cut;
+    }
+    if duration > start {
+        ranges.push(TimeRange {
+            start,
+            end: duration,
+        });
+    }
+    Ok(ranges)
+}
+
+/// Parse the `pts_time:<secs>` field out of an ffmpeg `showinfo` line.
+fn parse_pts_time(line: &str) -> Option<Duration> {
+    let rest = line.split("pts_time:").nth(1)?;
+    let secs: f64 = rest.split_whitespace().next()?.parse().ok()?;
+    Some(Duration::from_secs_f64(secs))
+}
+
+/// Read the input duration via ffprobe.
+async fn input_duration(input: &Path) -> anyhow::Result<Duration> {
+    let out = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(input)
+        .output()
+        .await
+        .context("ffprobe duration")?;
+    let secs: f64 = String::from_utf8_lossy(&out.stdout)
+        .trim()
+        .parse()
+        .context("could not parse input duration")?;
+    Ok(Duration::from_secs_f64(secs))
+}
+
 #[derive(Debug, Clone)]
 struct Sample {
     enc: sample_encode::Output,
@@ -219,7 +764,13 @@ struct Sample {
 }
 
 impl Sample {
-    fn print_attempt(&self, bar: &ProgressBar, min_vmaf: f32, max_encoded_percent: f32) {
+    fn print_attempt(
+        &self,
+        bar: &ProgressBar,
+        min_vmaf: f32,
+        max_encoded_percent: f32,
+        vmaf_percentile: f32,
+    ) {
         let crf_label = style("- crf").dim();
         let mut crf = style(self.crf);
         let samples = style(match self.samples {
@@ -227,7 +778,7 @@ impl Sample {
             _ => "",
         })
         .dim();
-        let vmaf_label = style("VMAF").dim();
+        let vmaf_label = style(vmaf_aggregate_label(vmaf_percentile)).dim();
         let mut vmaf = style(self.enc.vmaf);
         let mut percent = style!("{:.0}%", self.enc.predicted_encode_percent);
         let open = style("(").dim();
@@ -251,13 +802,18 @@ impl Sample {
 #[derive(Debug, Clone, Copy, clap::ArgEnum)]
 pub enum StdoutFormat {
     Human,
+    Json,
 }
 
 impl StdoutFormat {
-    fn print_result(self, Sample { crf, enc, .. }: &Sample) {
+    fn print_result(self, Sample { crf, enc, .. }: &Sample, vmaf_percentile: f32) {
         match self {
+            // json is serialized at the call site where the attempt history is
+            // available, see `print_result_json`
+            Self::Json => {}
             Self::Human => {
                 let crf = style(crf).bold().green();
+                let vmaf_label = vmaf_aggregate_label(vmaf_percentile);
                 let vmaf = style(enc.vmaf).bold().green();
                 let size = style(HumanBytes(enc.predicted_encode_size)).bold().green();
                 let percent = style!("{}%", enc.predicted_encode_percent.round())
@@ -265,13 +821,134 @@ impl StdoutFormat {
                     .green();
                 let time = style(HumanDuration(enc.predicted_encode_time)).bold();
                 println!(
-                    "crf {crf} VMAF {vmaf:.2} predicted full encode size {size} ({percent}) taking {time}"
+                    "crf {crf} {vmaf_label} {vmaf:.2} predicted full encode size {size} ({percent}) taking {time}"
                 );
             }
         }
     }
 }
 
+/// Label describing which VMAF aggregation is being reported: the mean, or the
+/// configured per-frame percentile.
+fn vmaf_aggregate_label(vmaf_percentile: f32) -> String {
+    if vmaf_percentile >= 100.0 {
+        "VMAF".into()
+    } else {
+        format!("VMAF {:.0}%ile", vmaf_percentile)
+    }
+}
+
+/// Predict the crf delivering `min_vmaf` by fitting a spline to every probe
+/// gathered so far, rather than lerping between just the two bracketing samples.
+///
+/// The attempts are sorted by crf and a natural cubic spline is fitted with crf
+/// on the x-axis. Since VMAF is monotonically decreasing in crf we invert by
+/// sampling the spline at each integer crf strictly between `better_q.crf` and
+/// `worse_q.crf` and picking the crf whose predicted VMAF is closest to - but not
+/// below - the target. With fewer than three points we fall back to the two-point
+/// lerp. Restricting the scan to that bracket (rather than the full `[min_crf,
+/// max_crf]` range) preserves the bracketing invariant: a crf outside it has
+/// already been tried and rejected, or is flat-clamped to by `CubicSpline::sample`
+/// and so carries no new information.
+fn vmaf_crf_spline(
+    min_vmaf: f32,
+    crf_attempts: &[Sample],
+    worse_q: &Sample,
+    better_q: &Sample,
+) -> u8 {
+    let lo = better_q.crf.saturating_add(1);
+    let hi = worse_q.crf.saturating_sub(1);
+    if lo > hi {
+        return vmaf_lerp_crf(min_vmaf, worse_q, better_q);
+    }
+
+    let mut points: Vec<(f64, f64)> = crf_attempts
+        .iter()
+        .map(|s| (s.crf as f64, s.enc.vmaf as f64))
+        .collect();
+    points.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("crf is never NaN"));
+    points.dedup_by(|a, b| a.0 == b.0);
+
+    // not enough points to fit a spline, use the bracketing samples
+    if points.len() < 3 {
+        return vmaf_lerp_crf(min_vmaf, worse_q, better_q);
+    }
+
+    let spline = CubicSpline::natural(&points);
+
+    let mut best: Option<(u8, f64)> = None;
+    for crf in lo..=hi {
+        let vmaf = spline.sample(crf as f64);
+        // never go below target - percentile/mean quality must be met
+        if vmaf + f64::EPSILON < min_vmaf as f64 {
+            continue;
+        }
+        let over = vmaf - min_vmaf as f64;
+        if best.map_or(true, |(_, best_over)| over < best_over) {
+            best = Some((crf, over));
+        }
+    }
+
+    match best {
+        Some((crf, _)) => crf,
+        // every sampled crf fell below target, defer to the lerp
+        None => vmaf_lerp_crf(min_vmaf, worse_q, better_q),
+    }
+}
+
+/// Natural cubic spline through a set of `(x, y)` knots sorted ascending by `x`.
+struct CubicSpline {
+    xs: Vec<f64>,
+    ys: Vec<f64>,
+    /// Second derivatives at each knot.
+    y2: Vec<f64>,
+}
+
+impl CubicSpline {
+    /// Fit a spline with "natural" end conditions (zero second derivative at the
+    /// boundaries). `points` must be sorted ascending by x with at least 2 knots.
+    fn natural(points: &[(f64, f64)]) -> Self {
+        let n = points.len();
+        let xs: Vec<f64> = points.iter().map(|p| p.0).collect();
+        let ys: Vec<f64> = points.iter().map(|p| p.1).collect();
+        let mut y2 = vec![0.0; n];
+        let mut u = vec![0.0; n];
+
+        for i in 1..n - 1 {
+            let sig = (xs[i] - xs[i - 1]) / (xs[i + 1] - xs[i - 1]);
+            let p = sig * y2[i - 1] + 2.0;
+            y2[i] = (sig - 1.0) / p;
+            let slope_diff = (ys[i + 1] - ys[i]) / (xs[i + 1] - xs[i])
+                - (ys[i] - ys[i - 1]) / (xs[i] - xs[i - 1]);
+            u[i] = (6.0 * slope_diff / (xs[i + 1] - xs[i - 1]) - sig * u[i - 1]) / p;
+        }
+        for k in (0..n - 1).rev() {
+            y2[k] = y2[k] * y2[k + 1] + u[k];
+        }
+
+        Self { xs, ys, y2 }
+    }
+
+    /// Interpolate y at `x`, clamping to the fitted range at the ends.
+    fn sample(&self, x: f64) -> f64 {
+        let n = self.xs.len();
+        if x <= self.xs[0] {
+            return self.ys[0];
+        }
+        if x >= self.xs[n - 1] {
+            return self.ys[n - 1];
+        }
+        let hi = self.xs.partition_point(|&xk| xk <= x).max(1);
+        let lo = hi - 1;
+        let h = self.xs[hi] - self.xs[lo];
+        let a = (self.xs[hi] - x) / h;
+        let b = (x - self.xs[lo]) / h;
+        a * self.ys[lo]
+            + b * self.ys[hi]
+            + ((a * a * a - a) * self.y2[lo] + (b * b * b - b) * self.y2[hi]) * h * h / 6.0
+    }
+}
+
 /// Produce a crf value between given samples using vmaf score linear interpolation.
 fn vmaf_lerp_crf(min_vmaf: f32, worse_q: &Sample, better_q: &Sample) -> u8 {
     assert!(
@@ -0,0 +1,290 @@
+use crate::command::crf_search::{Encoder, ProbeFailure};
+use anyhow::Context;
+use indicatif::ProgressBar;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::process::Command;
+
+/// Length of each sampled window.
+const SAMPLE_LEN: Duration = Duration::from_secs(20);
+
+/// Arguments for a single sample-encode probe: encode a handful of sample
+/// windows at a given crf and report the resulting quality & size. `preset` is
+/// not passed to ffmpeg; the encoder runs at its own default preset.
+#[derive(Clone)]
+pub struct Args {
+    pub input: PathBuf,
+    pub crf: u8,
+    pub preset: u8,
+    pub samples: u64,
+    pub keep: bool,
+    pub stdout_format: StdoutFormat,
+    /// Percentile of the per-frame VMAF distribution to report as `Output::vmaf`,
+    /// instead of the mean. See `aggregate_vmaf`.
+    pub vmaf_percentile: f32,
+    pub time_range: Option<(Duration, Duration)>,
+    pub encoder: Encoder,
+}
+
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+pub enum StdoutFormat {
+    Human,
+    Json,
+}
+
+/// Result of a sample-encode probe.
+#[derive(Debug, Clone)]
+pub struct Output {
+    /// Aggregate VMAF score: `Args::vmaf_percentile` of `vmaf_scores`, or the
+    /// mean when `vmaf_percentile` is 100.
+    pub vmaf: f32,
+    /// Per-frame VMAF scores the aggregate above was computed from, kept so
+    /// callers can inspect the distribution or re-aggregate at a different
+    /// percentile without re-encoding.
+    pub vmaf_scores: Vec<f32>,
+    pub predicted_encode_size: u64,
+    pub predicted_encode_percent: f64,
+    pub predicted_encode_time: Duration,
+}
+
+/// Encode `args.samples` windows of the input with `args.encoder` at
+/// `args.crf`, score each against the source with vmaf, and scale the sampled
+/// size/time up to predict the full encode.
+pub async fn run(args: Args, _bar: ProgressBar) -> anyhow::Result<Output> {
+    // restrict sampling to the given time range (e.g. a single detected
+    // scene), or the whole input when none was given -- avoid probing the
+    // input's duration at all when a range was already supplied
+    let (range_start, range_duration) = match args.time_range {
+        Some((start, end)) => (start, end - start),
+        None => (Duration::ZERO, probe_duration(&args.input).await?),
+    };
+    let windows = sample_windows(range_start, range_duration, args.samples.max(1));
+
+    let mut vmaf_scores = Vec::new();
+    let mut sampled_size = 0_u64;
+    let mut sampled_encode_time = Duration::ZERO;
+    let mut sampled_secs = 0.0_f64;
+
+    for (idx, window) in windows.iter().enumerate() {
+        let (size, encode_time, mut scores) = encode_and_score_sample(&args, idx, *window).await?;
+        sampled_size += size;
+        sampled_encode_time += encode_time;
+        sampled_secs += window.1.as_secs_f64();
+        vmaf_scores.append(&mut scores);
+    }
+
+    let vmaf = aggregate_vmaf(&mut vmaf_scores.clone(), args.vmaf_percentile);
+    let input_size = tokio::fs::metadata(&args.input)
+        .await
+        .context("failed to read input size")?
+        .len();
+
+    // scale what the sampled windows actually cost up to the full range being
+    // searched (the whole input, or just the current scene)
+    let scale = range_duration.as_secs_f64() / sampled_secs.max(f64::EPSILON);
+    let predicted_encode_size = (sampled_size as f64 * scale).round() as u64;
+    let predicted_encode_time = Duration::from_secs_f64(sampled_encode_time.as_secs_f64() * scale);
+
+    Ok(Output {
+        vmaf,
+        vmaf_scores,
+        predicted_encode_size,
+        predicted_encode_percent: predicted_encode_size as f64 / input_size.max(1) as f64 * 100.0,
+        predicted_encode_time,
+    })
+}
+
+/// Duration of `input`, as reported by ffprobe.
+async fn probe_duration(input: &Path) -> anyhow::Result<Duration> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration", "-of", "csv=p=0"])
+        .arg(input)
+        .output()
+        .await
+        .context("failed to run ffprobe")?;
+    anyhow::ensure!(output.status.success(), ProbeFailure(output.stderr));
+
+    let secs: f64 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .context("failed to parse ffprobe duration")?;
+    Ok(Duration::from_secs_f64(secs))
+}
+
+/// `n` windows of up to `SAMPLE_LEN` each, evenly spaced across
+/// `[start, start + duration)`.
+fn sample_windows(start: Duration, duration: Duration, n: u64) -> Vec<(Duration, Duration)> {
+    let n = n.max(1);
+    let window = SAMPLE_LEN.min(duration / n as u32);
+    (0..n)
+        .map(|i| {
+            let offset = duration.as_secs_f64() * i as f64 / n as f64;
+            (start + Duration::from_secs_f64(offset), window)
+        })
+        .collect()
+}
+
+/// Encode a single sample window and return its encoded size, wall-clock
+/// encode time, and per-frame vmaf scores against the matching slice of the
+/// source.
+async fn encode_and_score_sample(
+    args: &Args,
+    idx: usize,
+    (start, duration): (Duration, Duration),
+) -> anyhow::Result<(u64, Duration, Vec<f32>)> {
+    let encoder = args.encoder;
+    let probe_tag = format!("ab-av1-{}-{}-{idx}", std::process::id(), args.crf);
+    let sample_path = std::env::temp_dir().join(format!("{probe_tag}.mkv"));
+    let passlogfile = std::env::temp_dir().join(&probe_tag);
+
+    let started = Instant::now();
+    for pass in 1..=encoder.default_passes() {
+        let output = ffmpeg_command(args, encoder, pass, start, duration, &passlogfile, &sample_path)
+            .output()
+            .await
+            .with_context(|| format!("failed to run ffmpeg for crf {}", args.crf))?;
+        anyhow::ensure!(output.status.success(), ProbeFailure(output.stderr));
+    }
+    let encode_time = started.elapsed();
+
+    let size = tokio::fs::metadata(&sample_path)
+        .await
+        .with_context(|| format!("failed to read sample output for crf {}", args.crf))?
+        .len();
+
+    let scores = vmaf_scores(&args.input, &sample_path, start, duration).await?;
+
+    if encoder.default_passes() > 1 {
+        let _ = tokio::fs::remove_file(format!("{}-0.log", passlogfile.display())).await;
+        let _ = tokio::fs::remove_file(format!("{}-0.log.mbtree", passlogfile.display())).await;
+    }
+    if !args.keep {
+        let _ = tokio::fs::remove_file(&sample_path).await;
+    }
+
+    Ok((size, encode_time, scores))
+}
+
+/// Build the ffmpeg invocation for one encoder pass over `[start, start +
+/// duration)`, carrying the encoder's quality flag and any extra arguments it
+/// requires (e.g. an unbounded target bitrate for constant-quality modes).
+fn ffmpeg_command(
+    args: &Args,
+    encoder: Encoder,
+    pass: u8,
+    start: Duration,
+    duration: Duration,
+    passlogfile: &Path,
+    output: &Path,
+) -> Command {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-y")
+        .arg("-ss")
+        .arg(start.as_secs_f64().to_string())
+        .arg("-t")
+        .arg(duration.as_secs_f64().to_string())
+        .arg("-i")
+        .arg(&args.input)
+        .arg("-c:v")
+        .arg(encoder.codec_name())
+        .arg(encoder.quality_flag())
+        .arg(args.crf.to_string())
+        .args(encoder.default_args());
+    if encoder.default_passes() > 1 {
+        // under the temp dir, and unique per probe, so concurrent 2-pass
+        // probes at different crfs don't clobber each other's passlog under
+        // ffmpeg's shared default name/location
+        cmd.arg("-pass").arg(pass.to_string()).arg("-passlogfile").arg(passlogfile);
+    }
+    if pass < encoder.default_passes() {
+        // analysis-only pass(es): no output needed
+        cmd.arg("-f").arg("null").arg("-");
+    } else {
+        cmd.arg(output);
+    }
+    cmd
+}
+
+/// Score `distorted` against the `[start, start + duration)` slice of
+/// `reference` with ffmpeg's libvmaf filter, returning the per-frame scores.
+async fn vmaf_scores(
+    reference: &Path,
+    distorted: &Path,
+    start: Duration,
+    duration: Duration,
+) -> anyhow::Result<Vec<f32>> {
+    // keyed off the sample's own (already crf/sample-index-unique) filename so
+    // concurrent probes at different crfs don't race on the same log file
+    let log_path = std::env::temp_dir().join(format!(
+        "ab-av1-vmaf-{}.json",
+        distorted
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("sample")
+    ));
+
+    // the distorted sample was encoded from this slice alone, so it already
+    // starts at 0; only the reference is seeked
+    let output = Command::new("ffmpeg")
+        .arg("-y")
+        .arg("-i")
+        .arg(distorted)
+        .arg("-ss")
+        .arg(start.as_secs_f64().to_string())
+        .arg("-t")
+        .arg(duration.as_secs_f64().to_string())
+        .arg("-i")
+        .arg(reference)
+        .arg("-lavfi")
+        .arg(format!(
+            "[0:v]setpts=PTS-STARTPTS[dist];[1:v]setpts=PTS-STARTPTS[ref];[dist][ref]libvmaf=log_path={}:log_fmt=json",
+            log_path.display()
+        ))
+        .arg("-f")
+        .arg("null")
+        .arg("-")
+        .output()
+        .await
+        .context("failed to run ffmpeg libvmaf filter")?;
+    anyhow::ensure!(output.status.success(), ProbeFailure(output.stderr));
+
+    let log = tokio::fs::read_to_string(&log_path)
+        .await
+        .context("failed to read vmaf log")?;
+    let _ = tokio::fs::remove_file(&log_path).await;
+
+    parse_vmaf_log(&log)
+}
+
+/// Extract each frame's `vmaf` metric from libvmaf's json log output.
+fn parse_vmaf_log(log: &str) -> anyhow::Result<Vec<f32>> {
+    let json: serde_json::Value = serde_json::from_str(log).context("failed to parse vmaf log")?;
+    json["frames"]
+        .as_array()
+        .context("vmaf log missing frames")?
+        .iter()
+        .map(|frame| {
+            frame["metrics"]["vmaf"]
+                .as_f64()
+                .map(|v| v as f32)
+                .context("vmaf log frame missing vmaf metric")
+        })
+        .collect()
+}
+
+/// Aggregate per-frame vmaf scores at `percentile` (e.g. 25 = the worst
+/// quartile), or the mean when `percentile` is 100. Sorts `scores` in place
+/// unless taking the mean.
+fn aggregate_vmaf(scores: &mut [f32], percentile: f32) -> f32 {
+    if scores.is_empty() {
+        return 0.0;
+    }
+    if percentile >= 100.0 {
+        return scores.iter().sum::<f32>() / scores.len() as f32;
+    }
+    scores.sort_by(|a, b| a.partial_cmp(b).expect("vmaf score is never NaN"));
+    let idx = ((percentile / 100.0) * (scores.len() - 1) as f32).round() as usize;
+    scores[idx.min(scores.len() - 1)]
+}